@@ -0,0 +1,311 @@
+//
+// Sysinfo
+//
+// Copyright (c) 2018 Guillaume Gomez
+//
+
+use windows::system::is_proc_running;
+use Pid;
+use ProcessExt;
+use ProcessStatus;
+
+use std::mem::{size_of, zeroed};
+use std::ops::Deref;
+use std::ptr;
+
+use winapi::shared::minwindef::{FALSE, FILETIME};
+use winapi::um::handleapi::{CloseHandle, DuplicateHandle};
+use winapi::um::processthreadsapi::{
+    GetCurrentProcess, GetProcessIoCounters, GetProcessTimes, GetSystemTimes, OpenProcess,
+    TerminateProcess, IO_COUNTERS,
+};
+use winapi::um::psapi::{GetProcessMemoryInfo, PROCESS_MEMORY_COUNTERS};
+use winapi::um::winnt::{HANDLE, PROCESS_QUERY_INFORMATION, PROCESS_TERMINATE, PROCESS_VM_READ};
+
+// 100ns intervals between the Windows FILETIME epoch (1601-01-01) and the Unix epoch.
+const FILETIME_UNIX_EPOCH_DIFF_100NS: u64 = 116_444_736_000_000_000;
+
+fn filetime_as_u64(filetime: &FILETIME) -> u64 {
+    ((filetime.dwHighDateTime as u64) << 32) | u64::from(filetime.dwLowDateTime)
+}
+
+// Shared with `windows::system::boot_time`, which needs the same FILETIME -> Unix
+// timestamp conversion.
+pub(crate) fn filetime_to_unix_secs(filetime: &FILETIME) -> u64 {
+    filetime_as_u64(filetime).saturating_sub(FILETIME_UNIX_EPOCH_DIFF_100NS) / 10_000_000
+}
+
+// `HANDLE` is just a `*mut c_void` as far as the compiler is concerned, so it isn't
+// `Send`/`Sync` on its own. We only ever read from it (or hand it to winapi calls that
+// take it by value), never mutate what it points to from multiple threads at once, so
+// it's safe to move across the rayon thread pool used in `System::refresh_processes`.
+pub(crate) struct Handle(pub HANDLE);
+
+unsafe impl Send for Handle {}
+unsafe impl Sync for Handle {}
+
+impl Deref for Handle {
+    type Target = HANDLE;
+
+    fn deref(&self) -> &HANDLE {
+        &self.0
+    }
+}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        if !self.0.is_null() {
+            unsafe {
+                CloseHandle(self.0);
+            }
+        }
+    }
+}
+
+/// Struct containing a process' information.
+pub struct Process {
+    pub(crate) name: String,
+    pub(crate) pid: Pid,
+    pub(crate) memory: u64,
+    pub(crate) virtual_memory: u64,
+    pub(crate) cpu_usage: f32,
+    pub(crate) status: ProcessStatus,
+    pub(crate) handle: Handle,
+    pub(crate) old_cpu: u64,
+    pub(crate) old_sys_cpu: u64,
+    pub(crate) old_user_cpu: u64,
+    total_read_bytes: u64,
+    total_written_bytes: u64,
+    read_bytes: u64,
+    written_bytes: u64,
+    start_time: u64,
+}
+
+impl Process {
+    /// Returns the total number of bytes read by the process since it started.
+    pub fn disk_total_read_bytes(&self) -> u64 {
+        self.total_read_bytes
+    }
+
+    /// Returns the total number of bytes written by the process since it started.
+    pub fn disk_total_written_bytes(&self) -> u64 {
+        self.total_written_bytes
+    }
+
+    /// Returns the number of bytes read by the process since the last refresh.
+    pub fn disk_read_bytes(&self) -> u64 {
+        self.read_bytes
+    }
+
+    /// Returns the number of bytes written by the process since the last refresh.
+    pub fn disk_written_bytes(&self) -> u64 {
+        self.written_bytes
+    }
+
+    fn kill(&self) -> bool {
+        unsafe {
+            if !is_proc_running(self.handle.0) {
+                return false;
+            }
+            // The handle we already hold was opened for querying, not termination, so a
+            // fresh one with `PROCESS_TERMINATE` rights is needed here. Duplicating our
+            // own handle (rather than re-opening by PID) keeps it pinned to the exact
+            // kernel object `self` was constructed from, so a PID reused by a different
+            // process in the meantime can't be killed by mistake.
+            let mut terminate_handle: HANDLE = ptr::null_mut();
+            let duplicated = DuplicateHandle(
+                GetCurrentProcess(),
+                self.handle.0,
+                GetCurrentProcess(),
+                &mut terminate_handle,
+                PROCESS_TERMINATE,
+                FALSE,
+                0,
+            );
+            if duplicated == 0 || terminate_handle.is_null() {
+                return false;
+            }
+            let killed = TerminateProcess(terminate_handle, 1) != 0;
+            CloseHandle(terminate_handle);
+            killed
+        }
+    }
+}
+
+// The process creation time never changes over the lifetime of a process, so this only
+// needs to be queried once, at `Process` construction, rather than on every refresh.
+unsafe fn get_process_start_time(handle: HANDLE) -> u64 {
+    let (mut creation, mut exit, mut kernel, mut user): (FILETIME, FILETIME, FILETIME, FILETIME) =
+        (zeroed(), zeroed(), zeroed(), zeroed());
+    if GetProcessTimes(handle, &mut creation, &mut exit, &mut kernel, &mut user) == 0 {
+        return 0;
+    }
+    filetime_to_unix_secs(&creation)
+}
+
+impl Process {
+    pub(crate) fn new_from_pid(pid: Pid) -> Option<Process> {
+        unsafe {
+            let handle = OpenProcess(
+                PROCESS_QUERY_INFORMATION | PROCESS_VM_READ,
+                FALSE,
+                pid as u32,
+            );
+            if handle.is_null() {
+                return None;
+            }
+            let mut p = Process {
+                name: String::new(),
+                pid,
+                memory: 0,
+                virtual_memory: 0,
+                cpu_usage: 0.,
+                status: ProcessStatus::Run,
+                handle: Handle(handle),
+                old_cpu: 0,
+                old_sys_cpu: 0,
+                old_user_cpu: 0,
+                total_read_bytes: 0,
+                total_written_bytes: 0,
+                read_bytes: 0,
+                written_bytes: 0,
+                start_time: 0,
+            };
+            p.start_time = get_process_start_time(handle);
+            update_proc_info(&mut p);
+            // `update_proc_info` just computed a delta against the zeroed totals above,
+            // i.e. the process' entire lifetime I/O so far, not "since the last
+            // refresh". There is no previous refresh yet, so the first reported rate
+            // should be 0; the (now correctly seeded) totals are left untouched.
+            p.read_bytes = 0;
+            p.written_bytes = 0;
+            Some(p)
+        }
+    }
+}
+
+impl ProcessExt for Process {
+    fn new(pid: Pid, _parent: Option<Pid>, _start_time: u64) -> Process {
+        Process::new_from_pid(pid).unwrap_or_else(|| Process {
+            name: String::new(),
+            pid,
+            memory: 0,
+            virtual_memory: 0,
+            cpu_usage: 0.,
+            status: ProcessStatus::Unknown(0),
+            handle: Handle(::std::ptr::null_mut()),
+            old_cpu: 0,
+            old_sys_cpu: 0,
+            old_user_cpu: 0,
+            total_read_bytes: 0,
+            total_written_bytes: 0,
+            read_bytes: 0,
+            written_bytes: 0,
+            start_time: 0,
+        })
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn cpu_usage(&self) -> f32 {
+        self.cpu_usage
+    }
+
+    fn memory(&self) -> u64 {
+        self.memory
+    }
+
+    fn virtual_memory(&self) -> u64 {
+        self.virtual_memory
+    }
+
+    fn pid(&self) -> Pid {
+        self.pid
+    }
+
+    fn status(&self) -> ProcessStatus {
+        self.status
+    }
+
+    fn start_time(&self) -> u64 {
+        self.start_time
+    }
+
+    fn kill(&self) -> bool {
+        Process::kill(self)
+    }
+}
+
+pub(crate) fn get_handle(p: &Process) -> HANDLE {
+    p.handle.0
+}
+
+/// System-wide kernel (which, per `GetSystemTimes`, includes idle) and user time, in
+/// 100ns units. Sampled once per refresh and handed to every `compute_cpu_usage` call
+/// below so all processes are measured against the same window.
+pub(crate) fn get_system_computation_time() -> (u64, u64) {
+    unsafe {
+        let (mut idle, mut kernel, mut user): (FILETIME, FILETIME, FILETIME) =
+            (zeroed(), zeroed(), zeroed());
+        if GetSystemTimes(&mut idle, &mut kernel, &mut user) == 0 {
+            return (0, 0);
+        }
+        (filetime_as_u64(&kernel), filetime_as_u64(&user))
+    }
+}
+
+pub(crate) fn compute_cpu_usage(p: &mut Process, nb_processors: u64, system_time: (u64, u64)) {
+    unsafe {
+        let (mut creation, mut exit, mut kernel, mut user): (
+            FILETIME,
+            FILETIME,
+            FILETIME,
+            FILETIME,
+        ) = (zeroed(), zeroed(), zeroed(), zeroed());
+        if GetProcessTimes(p.handle.0, &mut creation, &mut exit, &mut kernel, &mut user) == 0 {
+            return;
+        }
+        let new_process_time = filetime_as_u64(&kernel) + filetime_as_u64(&user);
+        let (new_sys_kernel, new_sys_user) = system_time;
+
+        let process_delta = new_process_time.saturating_sub(p.old_cpu);
+        let system_delta = new_sys_kernel.saturating_sub(p.old_sys_cpu)
+            + new_sys_user.saturating_sub(p.old_user_cpu);
+
+        if system_delta > 0 {
+            p.cpu_usage =
+                (process_delta as f64 / system_delta as f64 * 100. * nb_processors as f64) as f32;
+        }
+
+        p.old_cpu = new_process_time;
+        p.old_sys_cpu = new_sys_kernel;
+        p.old_user_cpu = new_sys_user;
+    }
+}
+
+pub(crate) fn update_proc_info(p: &mut Process) {
+    unsafe {
+        let mut pmc: PROCESS_MEMORY_COUNTERS = zeroed();
+        if GetProcessMemoryInfo(
+            p.handle.0,
+            &mut pmc,
+            size_of::<PROCESS_MEMORY_COUNTERS>() as u32,
+        ) != 0
+        {
+            p.memory = pmc.WorkingSetSize as u64 / 1_000;
+            p.virtual_memory = pmc.PagefileUsage as u64 / 1_000;
+        }
+
+        let mut io_counters: IO_COUNTERS = zeroed();
+        if GetProcessIoCounters(p.handle.0, &mut io_counters) != 0 {
+            let total_read = io_counters.ReadTransferCount;
+            let total_written = io_counters.WriteTransferCount;
+            p.read_bytes = total_read.saturating_sub(p.total_read_bytes);
+            p.written_bytes = total_written.saturating_sub(p.total_written_bytes);
+            p.total_read_bytes = total_read;
+            p.total_written_bytes = total_written;
+        }
+    }
+}
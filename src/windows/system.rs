@@ -12,7 +12,6 @@ use sys::users::get_users;
 use std::cell::UnsafeCell;
 use std::collections::HashMap;
 use std::mem::{size_of, zeroed};
-use std::time::SystemTime;
 
 use LoadAvg;
 use Networks;
@@ -23,14 +22,22 @@ use SystemExt;
 use User;
 
 use windows::process::{
-    compute_cpu_usage, get_handle, get_system_computation_time, update_proc_info, Process,
+    compute_cpu_usage, filetime_to_unix_secs, get_handle, get_system_computation_time,
+    update_proc_info, Process,
 };
 use windows::tools::*;
 
 use winapi::shared::minwindef::FALSE;
+use winapi::um::handleapi::CloseHandle;
 use winapi::um::minwinbase::STILL_ACTIVE;
 use winapi::um::processthreadsapi::GetExitCodeProcess;
-use winapi::um::sysinfoapi::{GetTickCount64, GlobalMemoryStatusEx, MEMORYSTATUSEX};
+use winapi::um::realtimeapisets::QueryInterruptTime;
+use winapi::um::sysinfoapi::{
+    GetSystemTimeAsFileTime, GetTickCount64, GlobalMemoryStatusEx, MEMORYSTATUSEX,
+};
+use winapi::um::tlhelp32::{
+    CreateToolhelp32Snapshot, Process32First, Process32Next, PROCESSENTRY32, TH32CS_SNAPPROCESS,
+};
 use winapi::um::winnt::HANDLE;
 
 use rayon::prelude::*;
@@ -52,23 +59,41 @@ pub struct System {
     users: Vec<User>,
 }
 
-// Useful for parallel iterations.
-struct Wrap<T>(T);
-
-unsafe impl<T> Send for Wrap<T> {}
-unsafe impl<T> Sync for Wrap<T> {}
-
+// `QueryInterruptTime` gives the (biased) time elapsed since boot in 100ns units, which
+// — unlike the unbiased variant — *includes* time spent suspended, so subtracting it
+// from the current system time (`GetSystemTimeAsFileTime`) gives the boot time. The two
+// are read in consecutive calls, not as one atomic sample, so the result can be off by
+// the short gap between them. `GetTickCount64` is used instead if the interrupt-time
+// query fails.
 unsafe fn boot_time() -> u64 {
-    match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
-        Ok(n) => n.as_secs() - GetTickCount64() / 1000,
-        Err(_e) => {
-            #[cfg(feature = "debug")]
-            {
-                println!("Failed to compute boot time: {:?}", _e);
-            }
-            0
-        }
+    let mut filetime = zeroed();
+    GetSystemTimeAsFileTime(&mut filetime);
+    let now = filetime_to_unix_secs(&filetime);
+
+    let mut interrupt_time_100ns = 0u64;
+    if QueryInterruptTime(&mut interrupt_time_100ns) == 0 {
+        return now.saturating_sub(GetTickCount64() / 1000);
     }
+    now.saturating_sub(interrupt_time_100ns / 10_000_000)
+}
+
+// `ullTotalPageFile`/`ullAvailPageFile` report the whole commit charge limit (RAM + page
+// file), not just the page file, so the swap figures have to be derived by subtracting
+// out the physical memory already accounted for separately. Each side is saturated at
+// zero on its own (e.g. on machines with no page file at all, rounding in
+// `GlobalMemoryStatusEx` can make a page-file figure dip just under its physical
+// counterpart), but that alone doesn't guarantee `swap_free <= swap_total`, since the two
+// are derived from independent OS-reported pairs. Clamp `swap_free` down to `swap_total`
+// so `get_used_swap`'s plain `swap_total - swap_free` can't underflow.
+fn derive_swap(
+    total_page_file: u64,
+    avail_page_file: u64,
+    mem_total: u64,
+    mem_free: u64,
+) -> (u64, u64) {
+    let swap_total = total_page_file.saturating_sub(mem_total);
+    let swap_free = avail_page_file.saturating_sub(mem_free).min(swap_total);
+    (swap_total, swap_free)
 }
 
 impl SystemExt for System {
@@ -159,8 +184,16 @@ impl SystemExt for System {
             GlobalMemoryStatusEx(&mut mem_info);
             self.mem_total = auto_cast!(mem_info.ullTotalPhys, u64);
             self.mem_free = auto_cast!(mem_info.ullAvailPhys, u64);
-            //self.swap_total = auto_cast!(mem_info.ullTotalPageFile - mem_info.ullTotalPhys, u64);
-            //self.swap_free = auto_cast!(mem_info.ullAvailPageFile, u64);
+            let total_page_file = auto_cast!(mem_info.ullTotalPageFile, u64);
+            let avail_page_file = auto_cast!(mem_info.ullAvailPageFile, u64);
+            let (swap_total, swap_free) = derive_swap(
+                total_page_file,
+                avail_page_file,
+                self.mem_total,
+                self.mem_free,
+            );
+            self.swap_total = swap_total;
+            self.swap_free = swap_free;
         }
     }
 
@@ -186,7 +219,38 @@ impl SystemExt for System {
     }
 
     #[allow(clippy::cast_ptr_alignment)]
-    fn refresh_processes(&mut self) {}
+    fn refresh_processes(&mut self) {
+        let pids = unsafe { get_all_pids() };
+
+        for &pid in &pids {
+            if !self.process_list.contains_key(&pid) {
+                if let Some(p) = Process::new_from_pid(pid) {
+                    self.process_list.insert(pid, p);
+                }
+            } else if !refresh_existing_process(self, pid, false) {
+                // The PID was alive when we snapshotted it above but has since exited;
+                // drop the stale entry and, in the same pass, pick up whatever process
+                // (if any) the system has already recycled that PID to.
+                self.process_list.remove(&pid);
+                if let Some(p) = Process::new_from_pid(pid) {
+                    self.process_list.insert(pid, p);
+                }
+            }
+        }
+
+        let pids: std::collections::HashSet<usize> = pids.into_iter().collect();
+        self.process_list.retain(|pid, _| pids.contains(pid));
+
+        // `Process`'s `HANDLE` is wrapped in the `Handle` newtype (see
+        // `windows::process`), which is what makes it `Send`/`Sync` across the rayon
+        // thread pool; computing CPU usage is the expensive part of a refresh so it's
+        // the one piece we parallelize here.
+        let nb_processors = self.processors.len() as u64;
+        let system_time = get_system_computation_time();
+        self.process_list
+            .par_iter_mut()
+            .for_each(|(_, proc_)| compute_cpu_usage(proc_, nb_processors, system_time));
+    }
 
     fn refresh_disks_list(&mut self) {
         self.disks = unsafe { get_disks() };
@@ -283,7 +347,28 @@ impl Default for System {
     }
 }
 
-fn is_proc_running(handle: HANDLE) -> bool {
+#[allow(clippy::cast_ptr_alignment)]
+unsafe fn get_all_pids() -> Vec<Pid> {
+    let snapshot = CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0);
+    if snapshot.is_null() {
+        return Vec::new();
+    }
+    let mut pids = Vec::with_capacity(500);
+    let mut entry: PROCESSENTRY32 = zeroed();
+    entry.dwSize = size_of::<PROCESSENTRY32>() as u32;
+    if Process32First(snapshot, &mut entry) != FALSE {
+        loop {
+            pids.push(entry.th32ProcessID as usize);
+            if Process32Next(snapshot, &mut entry) == FALSE {
+                break;
+            }
+        }
+    }
+    CloseHandle(snapshot);
+    pids
+}
+
+pub(crate) fn is_proc_running(handle: HANDLE) -> bool {
     let mut exit_code = 0;
     let ret = unsafe { GetExitCodeProcess(handle, &mut exit_code) };
     !(ret == FALSE || exit_code != STILL_ACTIVE)
@@ -307,3 +392,41 @@ fn refresh_existing_process(s: &mut System, pid: Pid, compute_cpu: bool) -> bool
         false
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::derive_swap;
+
+    #[test]
+    fn swap_is_never_negative() {
+        let (swap_total, swap_free) = derive_swap(16_000, 4_000, 8_000, 2_000);
+        // `u64` can't go negative, but a wrong derivation could still saturate to 0
+        // instead of the real value, so check we got a non-trivial result back.
+        assert!(swap_total > 0);
+        assert!(swap_free <= swap_total);
+    }
+
+    #[test]
+    fn swap_free_is_clamped_to_swap_total() {
+        // RAM is nearly full (mem_free ~= 0) but the page file is barely committed
+        // (avail_page_file ~= total_page_file): naively subtracting independently
+        // would make swap_free > swap_total.
+        let (swap_total, swap_free) = derive_swap(10_000, 9_900, 10_000, 100);
+        assert_eq!(swap_total, 0);
+        assert_eq!(swap_free, 0);
+    }
+
+    #[test]
+    fn swap_matches_commit_charge() {
+        let total_page_file = 20_000;
+        let avail_page_file = 12_000;
+        let mem_total = 8_000;
+        let mem_free = 3_000;
+        let (swap_total, swap_free) =
+            derive_swap(total_page_file, avail_page_file, mem_total, mem_free);
+
+        assert_eq!(swap_total, total_page_file - mem_total);
+        assert_eq!(swap_free, avail_page_file - mem_free);
+        assert!(swap_free <= swap_total);
+    }
+}